@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::Table;
+
+/// Per-table checkpoint: where its `TABLE DATA` section starts and how
+/// many of its statements had already landed when the manifest was last
+/// written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TableProgress {
+    offset: u64,
+    statements: u64,
+    completed: u64,
+}
+
+/// Sidecar file recording import progress for a dump, so a killed process
+/// or a failed table can resume instead of re-running from scratch. Lives
+/// alongside the dump as `<dump>.batchr.json`.
+///
+/// Each batch is already wrapped in `BEGIN TRANSACTION; ... COMMIT
+/// TRANSACTION;`, so it either fully lands or not at all — `completed`
+/// only ever advances in whole-batch increments after a batch is
+/// confirmed committed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    file_len: u64,
+    tables: HashMap<String, TableProgress>,
+}
+
+impl Manifest {
+    /// The manifest path for a given dump file: `<dump>.batchr.json`.
+    pub fn path_for(dump_path: &str) -> PathBuf {
+        PathBuf::from(format!("{}.batchr.json", dump_path))
+    }
+
+    /// Builds a fresh, zero-progress manifest from the tables discovered
+    /// during the initial scan of the dump.
+    pub fn fresh(file_len: u64, tables: &[Table]) -> Self {
+        let tables = tables
+            .iter()
+            .map(|t| {
+                (
+                    t.name.clone(),
+                    TableProgress {
+                        offset: t.offset,
+                        statements: t.statements,
+                        completed: 0,
+                    },
+                )
+            })
+            .collect();
+        Self { file_len, tables }
+    }
+
+    /// Loads `<dump>.batchr.json` if present and parseable; any failure
+    /// (missing file, corrupt JSON) is treated as "no checkpoint" rather
+    /// than a hard error, since we can always fall back to a fresh import.
+    pub fn load(path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// A manifest only applies to the exact dump it was written against:
+    /// the same file length, and every table at the same offset with the
+    /// same statement count. Anything else means the dump changed shape
+    /// since the manifest was written, so its offsets can no longer be
+    /// trusted and we fall back to a fresh import.
+    pub fn matches(&self, file_len: u64, tables: &[Table]) -> bool {
+        if self.file_len != file_len || self.tables.len() != tables.len() {
+            return false;
+        }
+        tables.iter().all(|t| {
+            self.tables
+                .get(&t.name)
+                .is_some_and(|p| p.offset == t.offset && p.statements == t.statements)
+        })
+    }
+
+    /// Number of statements of `table` already confirmed committed.
+    pub fn completed(&self, table: &str) -> u64 {
+        self.tables.get(table).map(|p| p.completed).unwrap_or(0)
+    }
+
+    /// Records `completed` for `table` and returns a snapshot to pass to
+    /// `write_to`. Deliberately split from the write itself: this just
+    /// touches the in-memory map, so callers only need to hold the one
+    /// `Mutex<Manifest>` shared by every table's import task for this
+    /// quick update, not for the disk write that follows.
+    pub fn record_progress(&mut self, table: &str, completed: u64) -> Self {
+        if let Some(progress) = self.tables.get_mut(table) {
+            progress.completed = completed;
+        }
+        self.clone()
+    }
+
+    /// Rewrites the whole manifest to `path`. Called each time a table's
+    /// contiguous run of committed batches grows, so a kill at any point
+    /// resumes from the end of that run; batches that had landed beyond
+    /// it are resent. Snapshots from different tables can reach the disk
+    /// in a different order than they were taken, so the file may lag
+    /// further behind what has committed.
+    ///
+    /// Takes `self` by value (a snapshot from `record_progress`, not the
+    /// shared manifest) and runs the write — including the closing
+    /// `fsync` — on `spawn_blocking`, so one table's disk write never
+    /// blocks a Tokio worker thread or another table's concurrent commit.
+    /// Writes to the file are serialized with each other, so two of them
+    /// never interleave.
+    pub async fn write_to(self, path: &Path) -> Result<()> {
+        static WRITE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let path = path.to_owned();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let _guard = WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let file = std::fs::File::create(&path).context("Failed to write checkpoint manifest")?;
+            serde_json::to_writer_pretty(&file, &self).context("Failed to serialize checkpoint manifest")?;
+            file.sync_all()?;
+            Ok(())
+        })
+        .await
+        .context("Checkpoint manifest write task panicked")??;
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use rand::Rng;
+
+use crate::import_error::{first_real_error, ImportError};
+use crate::DumpFile;
+
+/// A `reqwest` error or HTTP status that's worth retrying, as opposed to one
+/// that represents a genuine client/data problem.
+///
+/// Mirrors the transient/permanent split sqlx draws around connection
+/// resets and serialization failures: a dropped connection or an
+/// overloaded server (429/502/503/504) is transient, while a 4xx or a
+/// successfully-parsed `"status": "ERR"` result is permanent and retrying
+/// it would just reproduce the same failure.
+fn is_transient_reqwest_error(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout()
+}
+
+fn is_transient_status(status: reqwest::StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 502 | 503 | 504)
+}
+
+fn env_duration_ms(key: &str, default_ms: u64) -> Duration {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(default_ms))
+}
+
+fn env_duration_secs(key: &str, default_secs: u64) -> Duration {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(default_secs))
+}
+
+fn env_u32(key: &str, default: u32) -> u32 {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(default)
+}
+
+#[derive(Clone)]
+pub struct DB {
+    http: reqwest::Client,
+    pub endpoint: String,
+    username: String,
+    password: String,
+    pub namespace: String,
+    database: String,
+    retry_initial_interval: Duration,
+    retry_max_interval: Duration,
+    retry_max_elapsed: Duration,
+    retry_max_attempts: u32,
+}
+
+impl DB {
+    pub fn new(endpoint: &str, username: &str, password: &str, namespace: &str, database: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            endpoint: endpoint.to_owned(),
+            username: username.to_owned(),
+            password: password.to_owned(),
+            namespace: namespace.to_owned(),
+            database: database.to_owned(),
+            retry_initial_interval: env_duration_ms("SURREALDB_RETRY_INITIAL_INTERVAL_MS", 250),
+            retry_max_interval: env_duration_ms("SURREALDB_RETRY_MAX_INTERVAL_MS", 30_000),
+            retry_max_elapsed: env_duration_secs("SURREALDB_RETRY_MAX_ELAPSED_SECS", 300),
+            retry_max_attempts: env_u32("SURREALDB_RETRY_MAX_ATTEMPTS", 20),
+        }
+    }
+
+    /// Sends a request built by `make_request`, retrying transient
+    /// connection/timeout errors and 429/502/503/504 responses with
+    /// exponential backoff and full jitter, starting at
+    /// `retry_initial_interval` and doubling up to `retry_max_interval`.
+    /// Gives up once `retry_max_attempts` is reached or `retry_max_elapsed`
+    /// has passed, returning whatever the last attempt produced.
+    async fn send_with_retry(
+        &self,
+        make_request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let start = Instant::now();
+        let mut interval = self.retry_initial_interval;
+        let mut attempt = 0u32;
+
+        loop {
+            attempt += 1;
+            let outcome = make_request().send().await;
+
+            let budget_exhausted =
+                attempt >= self.retry_max_attempts || start.elapsed() >= self.retry_max_elapsed;
+
+            match outcome {
+                Ok(res) if res.status().is_success() || !is_transient_status(res.status()) => {
+                    return Ok(res);
+                }
+                Ok(res) if budget_exhausted => return Ok(res),
+                Ok(_) => {}
+                Err(err) if !is_transient_reqwest_error(&err) || budget_exhausted => {
+                    return Err(err);
+                }
+                Err(_) => {}
+            }
+
+            let jitter: f64 = rand::thread_rng().gen_range(0.0..1.0);
+            tokio::time::sleep(interval.mul_f64(jitter)).await;
+            interval = std::cmp::min(interval.saturating_mul(2), self.retry_max_interval);
+        }
+    }
+
+    /// Imports `batch` as a single `OPTION IMPORT` transaction.
+    ///
+    /// `allow_existing` should be set for batches resent after resuming
+    /// from a checkpoint: a batch can commit server-side and still be
+    /// replayed, because the manifest only records progress *after* a
+    /// batch lands, and only up to the last contiguous run of landed
+    /// batches, so a crash leaves the checkpoint behind reality by every
+    /// batch that had landed beyond that point. Resending identical data then
+    /// deterministically reports every row as already existing — that's
+    /// not a failure, it's confirmation the previous run's commit made
+    /// it. Retrying such a batch wouldn't help (the conflict would recur
+    /// every time), so this is handled as an expected outcome rather than
+    /// as something to retry.
+    pub async fn import(&self, table: &str, completed: u64, batch: &Vec<String>, allow_existing: bool) -> Result<()> {
+        let sql = format!(
+            "BEGIN TRANSACTION;\nOPTION IMPORT;\n{}\nCOMMIT TRANSACTION;",
+            batch.join("\n")
+        );
+
+        let res = self
+            .send_with_retry(|| {
+                self.http
+                    .post(format!("{}/import", self.endpoint))
+                    .header("Accept", "application/json")
+                    .header("Surreal-NS", &self.namespace)
+                    .header("Surreal-DB", &self.database)
+                    .basic_auth(&self.username, Some(&self.password))
+                    .body(sql.clone())
+            })
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to run import query; error: {}\n{}",
+                res.status(),
+                res.text().await?,
+            ));
+        }
+
+        let results = res.json::<Vec<serde_json::Value>>().await?;
+        let mut errors = Vec::new();
+        for result in &results {
+            let status = result.get("status").context("Failed to parse result: no 'status' field")?;
+            let status = status.as_str().context("Failed to parse result: 'status' field is not a string")?;
+            if status == "ERR" {
+                errors.push(
+                    result.get("result").context("Failed to parse result: no 'result' field")?
+                        .as_str().context("Failed to parse result: 'result' field is not a string")?
+                        .to_owned()
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let real_error = first_real_error(&results, completed)?;
+        if allow_existing && matches!(real_error, Some((_, ImportError::RecordExists(_)))) {
+            return Ok(());
+        }
+
+        let dump = std::fs::File::create(format!("{}-Errors.json", table))?;
+        serde_json::to_writer_pretty(&dump, &DumpFile{
+            errors: errors.clone(),
+            queries: batch.clone(),
+        })?;
+        dump.sync_all()?;
+        drop(dump);
+
+        match real_error {
+            Some((statement_index, error)) => Err(anyhow::anyhow!(
+                "Error at index {}: {}",
+                statement_index,
+                error,
+            )),
+            None => Err(anyhow::anyhow!(
+                "Import of {} failed but no non-cascaded error was found among {} error(s).",
+                table,
+                errors.len(),
+            )),
+        }
+    }
+
+    /// Runs `sql` and returns the parsed per-statement results, so callers
+    /// that need the data back (e.g. `--verify`'s row counts) don't have to
+    /// re-implement request plumbing on top of `sql`.
+    pub async fn query_json(&self, sql: &str) -> Result<Vec<serde_json::Value>> {
+        let res = self
+            .send_with_retry(|| {
+                self.http
+                    .post(format!("{}/sql", self.endpoint))
+                    .header("Accept", "application/json")
+                    .header("Surreal-NS", &self.namespace)
+                    .header("Surreal-DB", &self.database)
+                    .basic_auth(&self.username, Some(&self.password))
+                    .body(sql.to_owned())
+            })
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to run sql query; error: {}\n{}\nSQL:{}",
+                res.status(),
+                res.text().await?,
+                sql,
+            ));
+        }
+
+        let results = res.json::<Vec<serde_json::Value>>().await?;
+        let mut errors = Vec::new();
+        for result in &results {
+            let status = result.get("status").context("Failed to parse result: no 'status' field")?;
+            let status = status.as_str().context("Failed to parse result: 'status' field is not a string")?;
+            if status == "ERR" {
+                errors.push(
+                    result.get("result").context("Failed to parse result: no 'result' field")?
+                        .as_str().context("Failed to parse result: 'result' field is not a string")?
+                        .to_owned()
+                );
+            }
+        }
+
+        if !errors.is_empty() {
+            let s = format!("Import errors:\n{}\n", errors.join("\n"));
+            let s2 = format!("SQL:\n{}\n", sql);
+            return Err(anyhow::anyhow!(s + &s2));
+        }
+
+        Ok(results)
+    }
+
+    pub async fn sql(&self, sql: &str) -> Result<()> {
+        self.query_json(sql).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+
+/// SurrealDB reports every error as a free-form `result` string, so
+/// there's no SQLSTATE-style code to switch on directly. This classifies
+/// those strings into the shapes we actually see and care about, the way
+/// postgres' SQLSTATE table buckets server errors by kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// This statement never ran — it was aborted because an earlier
+    /// statement in the same transaction failed. The real error is
+    /// whichever non-cascaded entry comes before it.
+    FailedTransaction,
+    /// A value didn't match the column/field type SurrealDB expected.
+    TypeMismatch(String),
+    /// A `DEFINE FIELD`/`DEFINE TABLE` assertion or schema constraint
+    /// rejected the row.
+    SchemaViolation(String),
+    /// `CREATE`/unique-index conflict on a record that already exists.
+    RecordExists(String),
+    /// Doesn't match any of the above; the raw message is kept for
+    /// debugging and surfaced to the user as-is.
+    Unknown(String),
+}
+
+impl ImportError {
+    fn classify(message: &str) -> Self {
+        if message.contains("not executed due to a failed transaction") {
+            ImportError::FailedTransaction
+        } else if message.contains("already contains") || message.contains("already exists") {
+            ImportError::RecordExists(message.to_owned())
+        } else if message.contains("Expected a") || message.contains("to convert") {
+            ImportError::TypeMismatch(message.to_owned())
+        } else if message.contains("FieldCheck") || message.contains("Found") && message.contains("for field") {
+            ImportError::SchemaViolation(message.to_owned())
+        } else {
+            ImportError::Unknown(message.to_owned())
+        }
+    }
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::FailedTransaction => write!(f, "not executed due to a failed transaction"),
+            ImportError::TypeMismatch(msg) => write!(f, "type mismatch: {}", msg),
+            ImportError::SchemaViolation(msg) => write!(f, "schema violation: {}", msg),
+            ImportError::RecordExists(msg) => write!(f, "record exists: {}", msg),
+            ImportError::Unknown(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// `DB::import` puts `BEGIN TRANSACTION;` and `OPTION IMPORT;` ahead of the
+/// caller's own statements in an import transaction, and each gets its own
+/// entry in the results array, so a batch statement's position there is
+/// offset from its position in `batch` by this many slots.
+const TRANSACTION_PREAMBLE_STATEMENTS: usize = 2;
+
+/// Walks `results` (the full per-statement response array for an import
+/// transaction) looking for the first error that isn't just cascade
+/// noise from an earlier failure, and maps its position back to an
+/// absolute statement index by dropping the
+/// `TRANSACTION_PREAMBLE_STATEMENTS` slots and adding `completed` (the
+/// number of this table's statements that come before the batch).
+pub fn first_real_error(
+    results: &[serde_json::Value],
+    completed: u64,
+) -> Result<Option<(u64, ImportError)>> {
+    for (position, result) in results.iter().enumerate() {
+        let status = result.get("status").context("Failed to parse result: no 'status' field")?;
+        let status = status.as_str().context("Failed to parse result: 'status' field is not a string")?;
+        if status != "ERR" {
+            continue;
+        }
+
+        let message = result.get("result").context("Failed to parse result: no 'result' field")?
+            .as_str().context("Failed to parse result: 'result' field is not a string")?;
+        let error = ImportError::classify(message);
+        if error == ImportError::FailedTransaction {
+            continue;
+        }
+
+        let statement_index = completed + position.saturating_sub(TRANSACTION_PREAMBLE_STATEMENTS) as u64;
+        return Ok(Some((statement_index, error)));
+    }
+
+    Ok(None)
+}
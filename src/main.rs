@@ -1,15 +1,25 @@
+mod checkpoint;
+mod db;
+mod import_error;
 mod parse;
+mod pipeline;
+mod verify;
 
 use std::io::SeekFrom;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use anyhow::{Context, Result};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
 use tokio::fs::File;
+use futures::future::join;
 use futures::{stream, StreamExt};
 use tokio::io::AsyncSeekExt;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
+use crate::checkpoint::Manifest;
+use crate::db::DB;
 use crate::parse::Statement;
+use crate::pipeline::{Batch, BatchSequencer};
 
 const BATCH_TARGET_BYTES: usize = 2000000;
 
@@ -29,10 +39,10 @@ impl std::fmt::Display for Section {
     }
 }
 
-struct Table {
-    name: String,
-    offset: u64,
-    statements: u64,
+pub(crate) struct Table {
+    pub(crate) name: String,
+    pub(crate) offset: u64,
+    pub(crate) statements: u64,
 }
 
 #[tokio::main]
@@ -45,14 +55,16 @@ async fn main() -> Result<()> {
         &std::env::var("SURREALDB_DATABASE")?,
     );
 
-    println!("Removing namespace: {}", &db.namespace);
-    db.sql(&format!(
-        "REMOVE NAMESPACE IF EXISTS {};",
-        &db.namespace,
-    )).await?;
-    println!("Removed namespace: {}", &db.namespace);
-
-    let filepath = std::env::args().nth(1).context("No file path provided")?;
+    // `--verify` checks row counts after importing; `--verify-only` skips
+    // straight to verification against an already-loaded database.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let verify_only = args.iter().any(|a| a == "--verify-only");
+    let verify = verify_only || args.iter().any(|a| a == "--verify");
+    let filepath = args
+        .iter()
+        .find(|a| !a.starts_with("--"))
+        .cloned()
+        .context("No file path provided")?;
 
     let file = File::open(&filepath).await.context("Failed to open file")?;
     let mut stream = parse::StatementStream::new(file);
@@ -135,242 +147,272 @@ async fn main() -> Result<()> {
 
     // dbg!(&definitions);
 
-    println!("Planning to import {} definition statements.", definitions.len());
-    db.import("Initial", 0, &definitions).await.context("Failed to import definitions")?;
-    println!("Successfully imported {} definition statements.", definitions.len());
-
-    println!("Planning to import {} tables.", tables.len());
-
-    let bars = Arc::new(Mutex::new(MultiProgress::new()));
-    let style = ProgressStyle::with_template(
-        "{msg}\nâ¤·[{elapsed_precise}] [{wide_bar}] {human_pos}/{human_len} [{eta_precise}]",
-    )?
-        .progress_chars("##-");
-
-    stream::iter(tables.iter())
-        .map(|table| {
-            let filepath = filepath.clone();
-            let db = db.clone();
-            let bars = bars.clone();
-            let style = style.clone();
-            async move {
-                let progress = {
-                    let bars = bars.lock().await;
-                    let bar = bars.add(ProgressBar::new(table.statements));
-                    drop(bars);
-                    bar
-                };
-                progress.set_style(style.clone());
-                progress.set_position(0);
-
-                let mut file = File::open(&filepath).await.unwrap();
-                file.seek(SeekFrom::Start(table.offset)).await.unwrap();
-                let mut stream = parse::StatementStream::new(file);
-                let mut completed = 0;
-
-                let mut batch = Vec::new();
-                let mut bytes = 0;
-                while completed < table.statements {
-                    while let Some(result) = stream.next_statement().await {
-                        let (_, result) = result.context("Failed to parse next statement").unwrap();
-                        match result {
-                            Statement::Comment(comment) => {
-                                let comment = comment.trim_matches(|c| c == '-' || c == ' ');
-                                let (prefix, _suffix) = if let Some(index) = comment.find(": ") {
-                                    comment.split_at(index)
-                                } else {
-                                    (comment, "")
-                                };
-
-                                let prefix = prefix.trim_matches(|c| c == ':' || c == ' ');
-                                // let suffix = suffix.trim_matches(|c| c == ':' || c == ' ');
-
-                                match prefix {
-                                    "" => {
-                                        continue;
-                                    },
-                                    // "OPTION" | "ACCESSES" | "FUNCTIONS" => {
-                                    //     continue;
-                                    // }
-                                    "TABLE" => {
-                                        {
-                                            let bars = bars.lock().await;
-                                            bars.remove(&progress);
-                                            drop(bars);
+    if !verify_only {
+        let manifest_path = Manifest::path_for(&filepath);
+        let file_len = tokio::fs::metadata(&filepath).await?.len();
+        let loaded_manifest = Manifest::load(&manifest_path);
+        let resuming = loaded_manifest.as_ref().is_some_and(|m| m.matches(file_len, &tables));
+        let manifest = Arc::new(Mutex::new(
+            if resuming { loaded_manifest.unwrap() } else { Manifest::fresh(file_len, &tables) }
+        ));
+
+        if resuming {
+            println!("Found matching checkpoint manifest at {}; resuming interrupted import.", manifest_path.display());
+        } else {
+            println!("Removing namespace: {}", &db.namespace);
+            db.sql(&format!(
+                "REMOVE NAMESPACE IF EXISTS {};",
+                &db.namespace,
+            )).await?;
+            println!("Removed namespace: {}", &db.namespace);
+
+            println!("Planning to import {} definition statements.", definitions.len());
+            db.import("Initial", 0, &definitions, false).await.context("Failed to import definitions")?;
+            println!("Successfully imported {} definition statements.", definitions.len());
+        }
+
+        println!("Planning to import {} tables.", tables.len());
+
+        let bars = Arc::new(Mutex::new(MultiProgress::new()));
+        let style = ProgressStyle::with_template(
+            "{msg}\nâ¤·[{elapsed_precise}] [{wide_bar}] {human_pos}/{human_len} [{eta_precise}]",
+        )?
+            .progress_chars("##-");
+
+        let results: Vec<Result<()>> = stream::iter(tables.iter())
+            .map(|table| {
+                let filepath = filepath.clone();
+                let db = db.clone();
+                let bars = bars.clone();
+                let style = style.clone();
+                let manifest = manifest.clone();
+                let manifest_path = manifest_path.clone();
+                async move {
+                    let completed = manifest.lock().await.completed(&table.name);
+
+                    let progress = {
+                        let bars = bars.lock().await;
+                        let bar = bars.add(ProgressBar::new(table.statements));
+                        drop(bars);
+                        bar
+                    };
+                    progress.set_style(style.clone());
+                    progress.set_position(completed);
+
+                    let mut file = File::open(&filepath).await.unwrap();
+                    file.seek(SeekFrom::Start(table.offset)).await.unwrap();
+                    let mut stream = parse::StatementStream::new(file);
+
+                    // Fast-forward past statements already committed in a
+                    // previous run, without re-sending them.
+                    let mut skipped = 0;
+                    while skipped < completed {
+                        match stream.next_statement().await {
+                            Some(Ok((_, Statement::Query(_)))) => skipped += 1,
+                            Some(Ok((_, Statement::Comment(_)))) => {}
+                            Some(Err(err)) => panic!("Failed to fast-forward {} to checkpoint: {}", table.name, err),
+                            None => break,
+                        }
+                    }
+
+                    // One task drives `StatementStream`, chunking into
+                    // `BATCH_TARGET_BYTES` batches pushed onto a bounded
+                    // channel; a small pool of consumers pulls batches off
+                    // it and imports them concurrently, so the reader
+                    // isn't idle while a batch is in flight over HTTP.
+                    let channel_depth = pipeline::env_usize("BATCHR_PIPELINE_DEPTH", 3);
+                    let consumer_count = pipeline::env_usize("BATCHR_PIPELINE_CONSUMERS", 4);
+                    let (tx, rx) = mpsc::channel::<Batch>(channel_depth);
+                    let rx = Arc::new(Mutex::new(rx));
+                    let committed_total = AtomicU64::new(completed);
+                    let sequencer = Mutex::new(BatchSequencer::starting_at(completed));
+                    // Set by a consumer on a hard import failure so the
+                    // producer stops feeding the channel and every other
+                    // consumer stops pulling from it, instead of racing on
+                    // to import the rest of the table behind a checkpoint
+                    // that can never advance past the failed batch.
+                    let failed = AtomicBool::new(false);
+                    let failure: Mutex<Option<String>> = Mutex::new(None);
+                    // Producer owns `tx`; dropping it when the producer
+                    // finishes is what makes `rx.recv()` return `None` and
+                    // lets the consumers below exit.
+                    let producer_progress = progress.clone();
+                    let producer_failed = &failed;
+
+                    let producer = async move {
+                        let mut seq = 0;
+                        let mut produced = 0;
+                        let mut batch = Vec::new();
+                        let mut bytes = 0;
+
+                        while produced < table.statements - completed {
+                            if producer_failed.load(Ordering::SeqCst) {
+                                return;
+                            }
+
+                            while let Some(result) = stream.next_statement().await {
+                                let (_, result) = result.context("Failed to parse next statement").unwrap();
+                                match result {
+                                    Statement::Comment(comment) => {
+                                        let comment = comment.trim_matches(|c| c == '-' || c == ' ');
+                                        let (prefix, _suffix) = if let Some(index) = comment.find(": ") {
+                                            comment.split_at(index)
+                                        } else {
+                                            (comment, "")
+                                        };
+
+                                        let prefix = prefix.trim_matches(|c| c == ':' || c == ' ');
+                                        // let suffix = suffix.trim_matches(|c| c == ':' || c == ' ');
+
+                                        match prefix {
+                                            "" => {
+                                                continue;
+                                            },
+                                            // "OPTION" | "ACCESSES" | "FUNCTIONS" => {
+                                            //     continue;
+                                            // }
+                                            "TABLE" => {
+                                                {
+                                                    let bars = bars.lock().await;
+                                                    bars.remove(&producer_progress);
+                                                    drop(bars);
+                                                }
+                                                break;
+                                            }
+                                            "TABLE DATA" => {
+                                                continue;
+                                            }
+                                            _ => producer_progress.set_message(format!("-- {}", comment)),
                                         }
-                                        break;
                                     }
-                                    "TABLE DATA" => {
-                                        continue;
+                                    Statement::Query(query) => {
+                                        let len = query.len();
+                                        bytes += len;
+                                        batch.push(query);
+                                        produced += 1;
+                                        if bytes >= BATCH_TARGET_BYTES {
+                                            break;
+                                        }
                                     }
-                                    _ => progress.set_message(format!("-- {}", comment)),
                                 }
                             }
-                            Statement::Query(query) => {
-                                let len = query.len();
-                                bytes += len;
-                                batch.push(query);
-                                if bytes >= BATCH_TARGET_BYTES {
+
+                            if !batch.is_empty() {
+                                if producer_failed.load(Ordering::SeqCst) {
+                                    return;
+                                }
+                                let this_seq = seq;
+                                seq += 1;
+                                let start_index = completed + produced - batch.len() as u64;
+                                if tx.send(Batch { seq: this_seq, start_index, statements: std::mem::take(&mut batch) }).await.is_err() {
+                                    return; // every consumer has gone away
+                                }
+                                bytes = 0;
+                            }
+                        }
+                    };
+
+                    let consumers = (0..consumer_count).map(|_| {
+                        let rx = rx.clone();
+                        let db = db.clone();
+                        let manifest = manifest.clone();
+                        let manifest_path = &manifest_path;
+                        let progress = &progress;
+                        let sequencer = &sequencer;
+                        let committed_total = &committed_total;
+                        let failed = &failed;
+                        let failure = &failure;
+                        async move {
+                            loop {
+                                if failed.load(Ordering::SeqCst) {
                                     break;
                                 }
+
+                                let batch = rx.lock().await.recv().await;
+                                let Some(batch) = batch else { break };
+                                let len = batch.statements.len() as u64;
+
+                                progress.set_message(format!(
+                                    "Importing {} data with {} statements:",
+                                    table.name, len,
+                                ));
+
+                                let allow_existing = resuming;
+                                match db.import(&table.name, batch.start_index, &batch.statements, allow_existing).await {
+                                    Ok(()) => {
+                                        let total = committed_total.fetch_add(len, Ordering::SeqCst) + len;
+                                        progress.set_position(total);
+                                        if let Some(contiguous) = sequencer.lock().await.ack(batch.seq, len) {
+                                            let snapshot = manifest.lock().await.record_progress(&table.name, contiguous);
+                                            snapshot
+                                                .write_to(manifest_path)
+                                                .await
+                                                .expect("Failed to write checkpoint manifest");
+                                        }
+                                    }
+                                    Err(err) => {
+                                        // A batch is always a whole committed-or-not
+                                        // transaction, and `DB::import` has already
+                                        // retried anything plausibly transient, so
+                                        // this is a hard failure: stop the producer
+                                        // and every other consumer instead of racing
+                                        // on past a checkpoint that can never advance
+                                        // beyond this batch again.
+                                        failed.store(true, Ordering::SeqCst);
+                                        let mut failure = failure.lock().await;
+                                        if failure.is_none() {
+                                            *failure = Some(format!("Failed to import {}: {}", table.name, err));
+                                        }
+                                        progress.set_message(format!("Failed to import {}: {}", table.name, err));
+                                        break;
+                                    }
+                                }
                             }
                         }
-                    }
+                    });
 
-                    progress.set_message(format!(
-                        "Importing {} data with {} statements and {} bytes:",
-                        table.name, batch.len(), bytes
-                    ));
+                    join(producer, futures::future::join_all(consumers)).await;
 
-                    if let Err(err) = db.import(&table.name, completed, &batch).await {
-                        progress.set_message(format!("Failed to import {}: {}", table.name, err.to_string()));
+                    let outcome = failure.lock().await.take();
+                    match outcome {
+                        Some(message) => Err(anyhow::anyhow!(message)),
+                        None => Ok(()),
                     }
-
-                    completed += batch.len() as u64;
-                    progress.set_position(completed);
-                    batch.clear();
-                    bytes = 0;
                 }
-            }
-        })
-        .buffer_unordered(10)
-        .collect::<Vec<_>>()
-        .await;
-
-    println!("Successfully imported {} tables.", tables.len());
-
-    Ok(())
-}
-
-#[derive(Clone)]
-struct DB {
-    http: reqwest::Client,
-    endpoint: String,
-    username: String,
-    password: String,
-    namespace: String,
-    database: String,
-}
-
-impl DB {
-    fn new(endpoint: &str, username: &str, password: &str, namespace: &str, database: &str) -> Self {
-        Self {
-            http: reqwest::Client::new(),
-            endpoint: endpoint.to_owned(),
-            username: username.to_owned(),
-            password: password.to_owned(),
-            namespace: namespace.to_owned(),
-            database: database.to_owned(),
-        }
-    }
-
-    async fn import(&self, table: &str, completed: u64, batch: &Vec<String>) -> Result<()> {
-        let sql = format!(
-            "BEGIN TRANSACTION;\nOPTION IMPORT;\n{}\nCOMMIT TRANSACTION;",
-            batch.join("\n")
-        );
-
-        let res = self.http
-            .post(format!("{}/import", self.endpoint))
-            .header("Accept", "application/json")
-            .header("Surreal-NS", &self.namespace)
-            .header("Surreal-DB", &self.database)
-            .basic_auth(&self.username, Some(&self.password))
-            .body(sql)
-            .send()
-            .await?;
-
-        if !res.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to run import query; error: {}\n{}",
-                res.status(),
-                res.text().await?,
-            ));
-        }
+            })
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await;
 
-        let results = res.json::<Vec<serde_json::Value>>().await?;
-        let mut errors = Vec::new();
+        let mut first_failure = None;
         for result in results {
-            let status = result.get("status").context("Failed to parse result: no 'status' field")?;
-            let status = status.as_str().context("Failed to parse result: 'status' field is not a string")?;
-            if status == "ERR" {
-                errors.push(
-                    result.get("result").context("Failed to parse result: no 'result' field")?
-                        .as_str().context("Failed to parse result: 'result' field is not a string")?
-                        .to_owned()
-                );
+            if let Err(err) = result {
+                eprintln!("{:#}", err);
+                first_failure.get_or_insert(err);
             }
         }
-
-        if !errors.is_empty() {
-            let dump = std::fs::File::create(format!("{}-Errors.json", table))?;
-            serde_json::to_writer_pretty(&dump, &DumpFile{
-                errors: errors.clone(),
-                queries: batch.clone(),
-            })?;
-            dump.sync_all()?;
-            drop(dump);
-
-            for (index, err) in errors.iter().enumerate() {
-                if !err.contains("not executed due to a failed transaction") {
-                    return Err(anyhow::anyhow!(
-                        "Error at index {}",
-                        index as u64 + completed,
-                    ));
-                }
-                return Err(anyhow::anyhow!("Error at unknown location."));
-            }
+        if let Some(err) = first_failure {
+            return Err(err.context("One or more tables failed to import"));
         }
 
-        Ok(())
+        println!("Successfully imported {} tables.", tables.len());
     }
 
-    async fn sql(&self, sql: &str) -> Result<()> {
-        let res = self.http
-            .post(format!("{}/sql", self.endpoint))
-            .header("Accept", "application/json")
-            .header("Surreal-NS", &self.namespace)
-            .header("Surreal-DB", &self.database)
-            .basic_auth(&self.username, Some(&self.password))
-            .body(sql.to_owned())
-            .send()
-            .await?;
-
-        if !res.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to run sql query; error: {}\n{}\nSQL:{}",
-                res.status(),
-                res.text().await?,
-                sql,
-            ));
+    if verify {
+        println!("Verifying imported row counts against the dump...");
+        let report = verify::verify(&db, &tables).await;
+        let all_ok = verify::print_report(&report);
+        if !all_ok {
+            return Err(anyhow::anyhow!("Verification found mismatched row counts"));
         }
-
-        let results = res.json::<Vec<serde_json::Value>>().await?;
-        let mut errors = Vec::new();
-        for result in results {
-            let status = result.get("status").context("Failed to parse result: no 'status' field")?;
-            let status = status.as_str().context("Failed to parse result: 'status' field is not a string")?;
-            if status == "ERR" {
-                errors.push(
-                    result.get("result").context("Failed to parse result: no 'result' field")?
-                        .as_str().context("Failed to parse result: 'result' field is not a string")?
-                        .to_owned()
-                );
-            }
-        }
-
-        if !errors.is_empty() {
-            let s = format!("Import errors:\n{}\n", errors.join("\n"));
-            let s2 = format!("SQL:\n{}\n", sql);
-            return Err(anyhow::anyhow!(s + &s2));
-        }
-
-        Ok(())
+        println!("Verification passed: all {} tables matched.", report.len());
     }
+
+    Ok(())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct DumpFile {
-    errors: Vec<String>,
-    queries: Vec<String>,
+pub struct DumpFile {
+    pub(crate) errors: Vec<String>,
+    pub(crate) queries: Vec<String>,
 }
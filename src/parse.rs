@@ -10,9 +10,14 @@ pub enum Statement {
 
 /// Streams `Statement`s out of any `AsyncRead + AsyncSeek` source.
 ///
-/// * Lines that begin with `--` are comments (newline **excluded**).
-/// * Everything else is part of a query until the first `;\n` terminator
-///   (terminator **included**).
+/// * Lines that begin with `--` are comments (newline **excluded**), but
+///   only outside of an in-progress query — a line starting with `--`
+///   inside a string literal or nested object is just more query text.
+/// * Everything else is part of a query until a `;` that sits outside any
+///   string literal and at brace/bracket nesting depth 0, immediately
+///   followed by a newline (terminator **included**). A `;\n` that shows
+///   up inside a string value or a nested `{...}`/`[...]` doesn't end the
+///   statement.
 ///
 /// Each `next_statement()` returns the byte offset **from the start of the
 /// file/stream** where that statement begins.
@@ -24,6 +29,11 @@ where
     pending: String,
     buf: Vec<u8>,
     stmt_start_pos: Option<u64>,
+    /// The quote character of the string literal we're currently inside,
+    /// or `None` if we're not inside one.
+    in_string: Option<char>,
+    /// Brace/bracket nesting depth of the statement built up so far.
+    depth: i32,
 }
 
 impl<R> StatementStream<R>
@@ -37,6 +47,8 @@ where
             pending: String::new(),
             buf: Vec::with_capacity(1024),
             stmt_start_pos: None,
+            in_string: None,
+            depth: 0,
         }
     }
 
@@ -45,6 +57,37 @@ where
         self.reader.into_inner()
     }
 
+    /// Feeds `line` through the string/nesting scanner, updating
+    /// `in_string` and `depth` in place. Handles SQL-style doubled-quote
+    /// escapes (`''`, `""`) and backslash escapes so a quote or brace that's
+    /// escaped inside a string doesn't flip our tracked state.
+    fn scan(&mut self, line: &str) {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if let Some(quote) = self.in_string {
+                if c == '\\' {
+                    chars.next();
+                    continue;
+                }
+                if c == quote {
+                    if chars.peek() == Some(&quote) {
+                        chars.next();
+                        continue;
+                    }
+                    self.in_string = None;
+                }
+                continue;
+            }
+
+            match c {
+                '\'' | '"' => self.in_string = Some(c),
+                '{' | '[' => self.depth += 1,
+                '}' | ']' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+
     /// Async counterpart of the synchronous version.
     ///
     /// Returns `None` at clean EOF.
@@ -67,15 +110,20 @@ where
                     // EOF mid-query ⇒ return what we have
                     let q = std::mem::take(&mut self.pending);
                     let start = self.stmt_start_pos.take().unwrap_or(0);
+                    self.in_string = None;
+                    self.depth = 0;
                     return Some(Ok((start, Statement::Query(q))));
                 }
                 Ok(n) => n,
                 Err(e) => return Some(Err(e)),
             };
 
-            // Validate UTF-8
+            // Validate UTF-8. Owned rather than borrowed from `self.buf` so
+            // the rest of the loop is free to take `&mut self` (via
+            // `self.scan`) without fighting the borrow checker over a
+            // reference into one of `self`'s own fields.
             let line = match std::str::from_utf8(&self.buf[..n]) {
-                Ok(s) => s,
+                Ok(s) => s.to_owned(),
                 Err(_) => {
                     return Some(Err(io::Error::new(
                         io::ErrorKind::InvalidData,
@@ -84,12 +132,9 @@ where
                 }
             };
 
-            // Comment fast-path
-            if line.starts_with("--") {
-                debug_assert!(
-                    self.pending.is_empty(),
-                    "comments inside statements are forbidden by spec"
-                );
+            // Comment fast-path: only between statements, never while a
+            // string literal or nested object is open.
+            if line.starts_with("--") && self.pending.is_empty() {
                 let comment = line.trim_end_matches('\n').to_owned();
                 return Some(Ok((pos_before_line, Statement::Comment(comment))));
             }
@@ -98,10 +143,14 @@ where
             if self.pending.is_empty() {
                 self.stmt_start_pos = Some(pos_before_line);
             }
-            self.pending.push_str(line);
+            self.pending.push_str(&line);
+            self.scan(&line);
 
-            // Query complete?
-            if self.pending.ends_with(";\n") {
+            // Query complete? A trailing `;\n` only terminates the
+            // statement if it's outside any string and at depth 0 — a
+            // `;\n` nested inside a string value or an unclosed
+            // `{...}`/`[...]` is just more statement text.
+            if self.in_string.is_none() && self.depth == 0 && self.pending.ends_with(";\n") {
                 let q = std::mem::take(&mut self.pending);
                 let start = self.stmt_start_pos.take().unwrap_or(pos_before_line);
                 return Some(Ok((start, Statement::Query(q))));
@@ -109,3 +158,55 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn queries(input: &str) -> Vec<String> {
+        let mut stream = StatementStream::new(std::io::Cursor::new(input.as_bytes().to_vec()));
+        let mut queries = Vec::new();
+        while let Some(result) = stream.next_statement().await {
+            let (_, statement) = result.expect("parse error");
+            if let Statement::Query(q) = statement {
+                queries.push(q);
+            }
+        }
+        queries
+    }
+
+    #[tokio::test]
+    async fn semicolon_inside_string_spans_lines_without_terminating() {
+        let input = "CREATE foo SET bar = 'a;\nb';\n";
+        let result = queries(input).await;
+        assert_eq!(result, vec![input.to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn doubled_quote_is_an_escape_not_a_close() {
+        let input = "CREATE x SET y = 'it''s a test;';\n";
+        let result = queries(input).await;
+        assert_eq!(result, vec![input.to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn backslash_escaped_quote_is_not_a_close() {
+        let input = "CREATE x SET y = \"a\\\"b;\";\n";
+        let result = queries(input).await;
+        assert_eq!(result, vec![input.to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn multiline_nested_object_spans_lines_without_terminating() {
+        let input = "CREATE x SET y = {\n  \"a\": 1;\n};\n";
+        let result = queries(input).await;
+        assert_eq!(result, vec![input.to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn plain_statement_terminates_on_its_own_semicolon() {
+        let input = "CREATE x SET y = 1;\n";
+        let result = queries(input).await;
+        assert_eq!(result, vec![input.to_owned()]);
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+/// One chunk of a table's data statements, handed from the producer task
+/// (driving `StatementStream`) to a pool of consumer tasks (calling
+/// `DB::import`) over a bounded channel, so parsing and network
+/// round-trips overlap instead of running strictly back-to-back.
+pub struct Batch {
+    /// Position of this batch within the table, in send order — consumer
+    /// tasks race each other, so this is what lets completions be put
+    /// back in order for the checkpoint manifest.
+    pub seq: u64,
+    /// How many of the table's statements had already been queued ahead
+    /// of this batch when it was produced; passed through to `DB::import`
+    /// so its error reporting still points at the right absolute index.
+    pub start_index: u64,
+    pub statements: Vec<String>,
+}
+
+/// Reduces batches completing out of order (consumers race each other)
+/// back down to a contiguous "statements committed from the start of the
+/// table" count that's safe to persist in the checkpoint manifest.
+/// Recording an out-of-order batch's own count directly would let a
+/// resume fast-forward past a batch that never actually landed, just
+/// because a later one happened to finish first.
+pub struct BatchSequencer {
+    next_seq: u64,
+    committed: u64,
+    pending: BTreeMap<u64, u64>,
+}
+
+impl BatchSequencer {
+    pub fn starting_at(committed: u64) -> Self {
+        Self { next_seq: 0, committed, pending: BTreeMap::new() }
+    }
+
+    /// Records that batch `seq` (holding `len` statements) finished
+    /// importing successfully. Returns the new contiguous total once this
+    /// unblocks one or more pending batches, or `None` while we're still
+    /// waiting on an earlier one to land.
+    pub fn ack(&mut self, seq: u64, len: u64) -> Option<u64> {
+        self.pending.insert(seq, len);
+        let mut advanced = false;
+        while let Some(&next_len) = self.pending.get(&self.next_seq) {
+            self.pending.remove(&self.next_seq);
+            self.committed += next_len;
+            self.next_seq += 1;
+            advanced = true;
+        }
+        advanced.then_some(self.committed)
+    }
+}
+
+/// Reads a `usize` knob from the environment, falling back to `default`
+/// for anything missing or unparseable.
+pub fn env_usize(key: &str, default: usize) -> usize {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acks_in_order_advance_immediately() {
+        let mut sequencer = BatchSequencer::starting_at(0);
+        assert_eq!(sequencer.ack(0, 5), Some(5));
+        assert_eq!(sequencer.ack(1, 3), Some(8));
+    }
+
+    #[test]
+    fn out_of_order_ack_waits_for_the_gap_to_fill() {
+        let mut sequencer = BatchSequencer::starting_at(0);
+        assert_eq!(sequencer.ack(1, 3), None);
+        assert_eq!(sequencer.ack(2, 2), None);
+        assert_eq!(sequencer.ack(0, 5), Some(10));
+    }
+
+    #[test]
+    fn starts_from_a_nonzero_checkpoint() {
+        let mut sequencer = BatchSequencer::starting_at(10);
+        assert_eq!(sequencer.ack(0, 5), Some(15));
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+
+use crate::db::DB;
+use crate::Table;
+
+/// Expected vs. actual row count for a single table, in the style of a
+/// sqllogictest expected-vs-actual diff.
+pub struct TableCount {
+    pub name: String,
+    pub expected: u64,
+    pub actual: Result<u64>,
+}
+
+impl TableCount {
+    pub fn is_ok(&self) -> bool {
+        matches!(&self.actual, Ok(actual) if *actual == self.expected)
+    }
+}
+
+async fn count_table(db: &DB, table: &str) -> Result<u64> {
+    let results = db
+        .query_json(&format!("SELECT count() FROM {} GROUP ALL;", table))
+        .await?;
+    let result = results.first().context("Empty response counting rows")?;
+    let rows = result.get("result").context("No 'result' field in count response")?;
+
+    // `GROUP ALL` on an empty table returns no rows at all rather than a
+    // row with count 0.
+    Ok(rows
+        .get(0)
+        .and_then(|row| row.get("count"))
+        .and_then(|count| count.as_u64())
+        .unwrap_or(0))
+}
+
+/// Compares the dump's expected row count for each table (the number of
+/// `Statement::Query` rows `main` already counted in its `TABLE DATA`
+/// section) against what `SELECT count() ... GROUP ALL` reports.
+pub async fn verify(db: &DB, tables: &[Table]) -> Vec<TableCount> {
+    let mut report = Vec::with_capacity(tables.len());
+    for table in tables {
+        report.push(TableCount {
+            name: table.name.clone(),
+            expected: table.statements,
+            actual: count_table(db, &table.name).await,
+        });
+    }
+    report
+}
+
+/// Prints the summary table and returns whether every row matched.
+pub fn print_report(report: &[TableCount]) -> bool {
+    println!("{:<32} {:>10} {:>10}  STATUS", "TABLE", "EXPECTED", "ACTUAL");
+    let mut all_ok = true;
+    for row in report {
+        all_ok &= row.is_ok();
+        let actual = match &row.actual {
+            Ok(actual) => actual.to_string(),
+            Err(err) => format!("error: {}", err),
+        };
+        let status = if row.is_ok() { "OK" } else { "MISMATCH" };
+        println!("{:<32} {:>10} {:>10}  {}", row.name, row.expected, actual, status);
+    }
+    all_ok
+}